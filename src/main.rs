@@ -4,6 +4,8 @@ use std::collections::HashMap;
 use std::fs::DirEntry;
 use std::path::{Component, Path, PathBuf};
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
 use once_cell::sync::Lazy;
 use regex_cursor::engines::meta::Regex;
@@ -18,15 +20,167 @@ use tower_lsp::{Client, LanguageServer, LspService, Server};
 #[derive(Debug)]
 struct Backend {
     client: Client,
-    document_map: Mutex<HashMap<String, Rope>>,
+    document_map: Arc<Mutex<HashMap<String, Rope>>>,
+    position_encoding: Mutex<PositionEncodingKind>,
+    crawl_config: Mutex<Crawl>,
+    workspace_root: Mutex<Option<PathBuf>>,
+    workspace_index: Mutex<Vec<String>>,
+    /// Per-document change counter used to debounce diagnostics recomputation.
+    diagnostics_version: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+/// Configuration for the optional workspace crawler, read from
+/// `initialization_options` and refreshed on `workspace/didChangeConfiguration`.
+#[derive(Debug, Clone)]
+struct Crawl {
+    /// Stop crawling once this many files have been indexed.
+    max_crawl_files: usize,
+    /// Stop crawling once the indexed paths exceed this many bytes.
+    max_crawl_memory: usize,
+    /// Index every file, ignoring `.gitignore` and hidden-file filtering.
+    all_files: bool,
+}
+
+impl Default for Crawl {
+    fn default() -> Self {
+        Self {
+            max_crawl_files: 10_000,
+            max_crawl_memory: 16 * 1024 * 1024,
+            all_files: false,
+        }
+    }
+}
+
+impl Crawl {
+    /// Parses a `Crawl` from a settings object, falling back to defaults for any
+    /// missing field. Accepts the fields either at the top level or nested under
+    /// a `"crawl"` key.
+    fn from_value(value: Option<&Value>) -> Self {
+        let mut crawl = Crawl::default();
+        let Some(value) = value else {
+            return crawl;
+        };
+        let value = value.get("crawl").unwrap_or(value);
+        if let Some(v) = value.get("max_crawl_files").and_then(Value::as_u64) {
+            crawl.max_crawl_files = v as usize;
+        }
+        if let Some(v) = value.get("max_crawl_memory").and_then(Value::as_u64) {
+            crawl.max_crawl_memory = v as usize;
+        }
+        if let Some(v) = value.get("all_files").and_then(Value::as_bool) {
+            crawl.all_files = v;
+        }
+        crawl
+    }
+}
+
+impl Backend {
+    /// Re-crawls the workspace root (if any) with the current configuration and
+    /// replaces the stored index.
+    async fn refresh_index(&self) {
+        let root = self.workspace_root.lock().await.clone();
+        let crawl = self.crawl_config.lock().await.clone();
+        if let Some(root) = root {
+            *self.workspace_index.lock().await = crawl_workspace(&root, &crawl);
+        }
+    }
+
+    /// Computes and publishes diagnostics for `uri` immediately.
+    async fn publish_diagnostics_now(&self, uri: Url) {
+        let key = uri.to_string();
+        // Bump the version so any in-flight debounced run is superseded.
+        *self
+            .diagnostics_version
+            .lock()
+            .await
+            .entry(key.clone())
+            .or_insert(0) += 1;
+        let encoding = self.position_encoding.lock().await.clone();
+        let diagnostics = {
+            let document_map = self.document_map.lock().await;
+            let Some(rope) = document_map.get(&key) else {
+                return;
+            };
+            compute_diagnostics(rope, &uri, &encoding)
+        };
+        self.client.publish_diagnostics(uri, diagnostics, None).await;
+    }
+
+    /// Schedules a debounced diagnostics recomputation for `uri`, so a burst of
+    /// keystrokes only triggers a single scan once the document settles.
+    async fn schedule_diagnostics(&self, uri: Url) {
+        let key = uri.to_string();
+        let version = {
+            let mut versions = self.diagnostics_version.lock().await;
+            let counter = versions.entry(key.clone()).or_insert(0);
+            *counter += 1;
+            *counter
+        };
+        let versions = self.diagnostics_version.clone();
+        let document_map = self.document_map.clone();
+        let client = self.client.clone();
+        let encoding = self.position_encoding.lock().await.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            // A newer change arrived while waiting; let that run win.
+            if versions.lock().await.get(&key).copied() != Some(version) {
+                return;
+            }
+            let diagnostics = {
+                let document_map = document_map.lock().await;
+                let Some(rope) = document_map.get(&key) else {
+                    return;
+                };
+                compute_diagnostics(rope, &uri, &encoding)
+            };
+            client.publish_diagnostics(uri, diagnostics, None).await;
+        });
+    }
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        // Negotiate the position encoding: clients advertise the ones they
+        // support in `general.positionEncodings`, and we pick UTF-8, then
+        // UTF-16, then UTF-32, taking the first of those the client lists.
+        // LSP defaults to UTF-16 when nothing is negotiated.
+        let encoding = params
+            .capabilities
+            .general
+            .and_then(|general| general.position_encodings)
+            .and_then(|encodings| {
+                [
+                    PositionEncodingKind::UTF8,
+                    PositionEncodingKind::UTF16,
+                    PositionEncodingKind::UTF32,
+                ]
+                .into_iter()
+                .find(|encoding| encodings.contains(encoding))
+            })
+            .unwrap_or(PositionEncodingKind::UTF16);
+        *self.position_encoding.lock().await = encoding.clone();
+
+        // Determine the workspace root and crawl it up front so project-wide
+        // completions are available immediately.
+        let root = params
+            .workspace_folders
+            .as_ref()
+            .and_then(|folders| folders.first())
+            .map(|folder| folder.uri.clone())
+            .or(params.root_uri)
+            .and_then(|uri| uri.to_file_path().ok());
+        let crawl = Crawl::from_value(params.initialization_options.as_ref());
+        *self.crawl_config.lock().await = crawl.clone();
+        *self.workspace_root.lock().await = root.clone();
+        if let Some(root) = &root {
+            *self.workspace_index.lock().await = crawl_workspace(root, &crawl);
+        }
+
         Ok(InitializeResult {
             server_info: None,
             capabilities: ServerCapabilities {
+                position_encoding: Some(encoding),
                 text_document_sync: Some(TextDocumentSyncCapability::Kind(
                     TextDocumentSyncKind::INCREMENTAL,
                 )),
@@ -37,6 +191,19 @@ impl LanguageServer for Backend {
                     trigger_characters: Some(vec!['.'.to_string(), '/'.to_string()]),
                     completion_item: None,
                 }),
+                document_link_provider: Some(DocumentLinkOptions {
+                    resolve_provider: Some(false),
+                    work_done_progress_options: Default::default(),
+                }),
+                definition_provider: Some(OneOf::Left(true)),
+                workspace: Some(WorkspaceServerCapabilities {
+                    workspace_folders: None,
+                    file_operations: Some(WorkspaceFileOperationsServerCapabilities {
+                        will_rename: Some(path_file_operation_options()),
+                        did_rename: Some(path_file_operation_options()),
+                        ..Default::default()
+                    }),
+                }),
                 ..ServerCapabilities::default()
             },
         })
@@ -58,16 +225,19 @@ impl LanguageServer for Backend {
             .await;
     }
 
-    async fn did_change_configuration(&self, _: DidChangeConfigurationParams) {
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
         self.client
             .log_message(MessageType::INFO, "configuration changed!")
             .await;
+        *self.crawl_config.lock().await = Crawl::from_value(Some(&params.settings));
+        self.refresh_index().await;
     }
 
     async fn did_change_watched_files(&self, _: DidChangeWatchedFilesParams) {
         self.client
             .log_message(MessageType::INFO, "watched files have changed!")
             .await;
+        self.refresh_index().await;
     }
 
     async fn execute_command(&self, _: ExecuteCommandParams) -> Result<Option<Value>> {
@@ -83,24 +253,28 @@ impl LanguageServer for Backend {
             .log_message(MessageType::INFO, "file opened!")
             .await;
         let rope = Rope::from_str(&params.text_document.text);
+        let uri = params.text_document.uri;
         self.document_map
             .lock()
             .await
-            .insert(params.text_document.uri.to_string(), rope);
+            .insert(uri.to_string(), rope);
+        self.publish_diagnostics_now(uri).await;
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
         self.client
             .log_message(MessageType::INFO, "file changed!")
             .await;
+        let document_uri = params.text_document.uri.clone();
         let uri = params.text_document.uri.to_string();
+        let encoding = self.position_encoding.lock().await.clone();
         let mut document_map = self.document_map.lock().await;
         for change in params.content_changes {
             if let Some(range) = change.range {
                 let rope = document_map.get_mut(&uri).unwrap();
 
-                let start = position_to_offset(rope, range.start);
-                let end = position_to_offset(rope, range.end);
+                let start = position_to_offset(rope, range.start, &encoding);
+                let end = position_to_offset(rope, range.end, &encoding);
 
                 rope.remove(start..end);
                 rope.insert(start, &change.text);
@@ -112,18 +286,36 @@ impl LanguageServer for Backend {
                 // document_map.insert(uri.clone(), Rope::from_str(&change.text));
             }
         }
+        drop(document_map);
+        self.schedule_diagnostics(document_uri).await;
     }
 
-    async fn did_save(&self, _: DidSaveTextDocumentParams) {
+    async fn did_save(&self, params: DidSaveTextDocumentParams) {
         self.client
             .log_message(MessageType::INFO, "file saved!")
             .await;
+        self.publish_diagnostics_now(params.text_document.uri).await;
     }
 
-    async fn did_close(&self, _: DidCloseTextDocumentParams) {
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
         self.client
             .log_message(MessageType::INFO, "file closed!")
             .await;
+        let uri = params.text_document.uri;
+        let key = uri.to_string();
+        // Drop the document and bump its version so any debounced diagnostics
+        // run still in flight is superseded and can't resurrect warnings.
+        self.document_map.lock().await.remove(&key);
+        *self
+            .diagnostics_version
+            .lock()
+            .await
+            .entry(key)
+            .or_insert(0) += 1;
+        // Clear diagnostics so stale warnings don't linger for closed files.
+        self.client
+            .publish_diagnostics(uri, Vec::new(), None)
+            .await;
     }
 
     async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
@@ -131,73 +323,669 @@ impl LanguageServer for Backend {
             .log_message(MessageType::INFO, "completion!")
             .await;
         let uri = params.text_document_position.text_document.uri;
+        let encoding = self.position_encoding.lock().await.clone();
         let document_map = self.document_map.lock().await;
         let position = params.text_document_position.position;
         let rope = document_map.get(&uri.to_string()).unwrap();
         let line_start = rope.line_to_char(position.line as usize);
-        let offset = line_start + position.character as usize;
+        let offset = position_to_offset(rope, position, &encoding);
         let line_until_cursor = rope.slice(line_start..offset);
 
-        let Some(dir_path) = get_path_suffix(line_until_cursor, false).and_then(|matched_path| {
-            let matched_path = Cow::from(matched_path);
-            let path: Cow<_> = if matched_path.starts_with("file://") {
-                Url::from_str(&matched_path)
-                    .ok()
-                    .and_then(|url| url.to_file_path().ok())?
-                    .into()
-            } else {
-                Path::new(&*matched_path).into()
-            };
-            let path = expand_tilde(path);
-            let parent_dir = uri.to_file_path().unwrap();
-            let parent_dir = parent_dir.parent();
-            let path = match parent_dir {
-                Some(parent_dir) if path.is_relative() => parent_dir.join(&path),
-                _ => path.into_owned(),
-            };
-            if matched_path.ends_with("/") {
-                Some(PathBuf::from(path.as_path()))
+        let mut items: Vec<CompletionItem> = Vec::new();
+
+        // Directory listing for the concrete directory under the cursor.
+        let matched = get_path_suffix(line_until_cursor, false).map(|m| Cow::from(m).into_owned());
+        let dir_path = matched.as_deref().and_then(|matched_path| {
+            let path = resolve_path(matched_path, &uri)?;
+            if matched_path.ends_with('/') {
+                Some(path)
             } else {
                 path.parent().map(PathBuf::from)
             }
-        }) else {
+        });
+        if let (Some(matched_path), Some(dir_path)) = (matched.as_deref(), dir_path.as_ref()) {
+            // The partial filename the user has typed after the final `/`.
+            let fragment = matched_path.rsplit('/').next().unwrap_or("");
+            if let Ok(entries) = read_dir_sorted(dir_path, false) {
+                // `entries` is already dirs-before-files then alphabetical, so
+                // its position gives us a stable tie-breaker for equal scores.
+                for (rank, dir_entry) in entries.into_iter().enumerate() {
+                    let file_name = dir_entry.file_name();
+                    let file_name_str = file_name.to_string_lossy().to_string();
+                    let Some(score) = fuzzy_score(&file_name_str, fragment) else {
+                        continue;
+                    };
+                    let kind = dir_entry.metadata().ok().and_then(|meta| {
+                        if meta.is_dir() {
+                            Some(CompletionItemKind::FOLDER)
+                        } else if meta.is_file() {
+                            Some(CompletionItemKind::FILE)
+                        } else {
+                            None
+                        }
+                    });
+                    // Higher score sorts first; ties fall back to the
+                    // directories-before-files ordering captured in `rank`.
+                    let key = (i64::MAX - score) as u64;
+                    let sort_text = format!("{key:020}{rank:06}");
+                    items.push(CompletionItem {
+                        label: file_name_str.clone(),
+                        detail: None,
+                        kind,
+                        sort_text: Some(sort_text),
+                        filter_text: Some(file_name_str),
+                        ..CompletionItem::default()
+                    });
+                }
+            }
+        }
+
+        // When the cursor sits on a bare fragment (no directory separator yet),
+        // offer project-wide completions from the crawled index.
+        if let Some(fragment) = get_path_suffix(line_until_cursor, true) {
+            let fragment = Cow::from(fragment);
+            if !fragment.contains('/') {
+                // Index paths are workspace-root relative, but the inserted text
+                // must resolve from the document's own directory.
+                let doc_parent = uri
+                    .to_file_path()
+                    .ok()
+                    .and_then(|path| path.parent().map(Path::to_path_buf));
+                let root = self.workspace_root.lock().await.clone();
+                let index = self.workspace_index.lock().await;
+
+                let mut scored: Vec<(i64, CompletionItem)> = Vec::new();
+                for relative in index.iter() {
+                    let Some(score) = fuzzy_score(relative, &fragment) else {
+                        continue;
+                    };
+                    let insert_text = match (&root, &doc_parent) {
+                        (Some(root), Some(doc_parent)) => {
+                            relative_path(&root.join(relative), doc_parent)
+                                .to_string_lossy()
+                                .into_owned()
+                        }
+                        _ => relative.clone(),
+                    };
+                    let key = (i64::MAX - score) as u64;
+                    scored.push((
+                        score,
+                        CompletionItem {
+                            label: relative.clone(),
+                            filter_text: Some(relative.clone()),
+                            insert_text: Some(insert_text),
+                            sort_text: Some(format!("{key:020}")),
+                            kind: Some(CompletionItemKind::FILE),
+                            ..CompletionItem::default()
+                        },
+                    ));
+                }
+                scored.sort_by(|a, b| b.0.cmp(&a.0));
+                items.extend(
+                    scored
+                        .into_iter()
+                        .take(MAX_INDEX_COMPLETIONS)
+                        .map(|(_, item)| item),
+                );
+            }
+        }
+
+        if items.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(CompletionResponse::Array(items)))
+        }
+    }
+
+    async fn document_link(
+        &self,
+        params: DocumentLinkParams,
+    ) -> Result<Option<Vec<DocumentLink>>> {
+        self.client
+            .log_message(MessageType::INFO, "document link!")
+            .await;
+        let uri = params.text_document.uri;
+        let encoding = self.position_encoding.lock().await.clone();
+        let document_map = self.document_map.lock().await;
+        let Some(rope) = document_map.get(&uri.to_string()) else {
             return Ok(None);
         };
 
-        let Ok(items) = read_dir_sorted(&dir_path, false) else {
+        let mut links = Vec::new();
+        for (start, end, matched) in find_path_matches(rope) {
+            let Some(resolved) = resolve_path(&matched, &uri) else {
+                continue;
+            };
+            if !resolved.exists() {
+                continue;
+            }
+            let Ok(target) = Url::from_file_path(&resolved) else {
+                continue;
+            };
+            let range = Range {
+                start: offset_to_position(rope, start, &encoding),
+                end: offset_to_position(rope, end, &encoding),
+            };
+            links.push(DocumentLink {
+                range,
+                target: Some(target),
+                tooltip: None,
+                data: None,
+            });
+        }
+
+        Ok(Some(links))
+    }
+
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>> {
+        self.client
+            .log_message(MessageType::INFO, "goto definition!")
+            .await;
+        let position = params.text_document_position_params.position;
+        let uri = params.text_document_position_params.text_document.uri;
+        let encoding = self.position_encoding.lock().await.clone();
+        let document_map = self.document_map.lock().await;
+        let Some(rope) = document_map.get(&uri.to_string()) else {
             return Ok(None);
         };
 
-        let items = items
-            .into_iter()
-            .map(|dir_entry| {
-                let file_name = dir_entry.file_name();
-                let file_name_str = file_name.to_string_lossy().to_string();
-                let kind = dir_entry.metadata().ok().and_then(|meta| {
-                    if meta.is_dir() {
-                        Some(CompletionItemKind::FOLDER)
-                    } else if meta.is_file() {
-                        Some(CompletionItemKind::FILE)
-                    } else {
-                        None
-                    }
-                });
-                CompletionItem {
-                    label: file_name_str,
-                    detail: None,
-                    kind,
-                    ..CompletionItem::default()
+        let cursor = rope.char_to_byte(position_to_offset(rope, position, &encoding));
+        for (start, end, matched) in find_path_matches(rope) {
+            if !(start..=end).contains(&cursor) {
+                continue;
+            }
+            let Some(resolved) = resolve_path(&matched, &uri) else {
+                break;
+            };
+            if !resolved.exists() {
+                break;
+            }
+            let Ok(target) = Url::from_file_path(&resolved) else {
+                break;
+            };
+            let start = Position {
+                line: 0,
+                character: 0,
+            };
+            let location = Location {
+                uri: target,
+                range: Range { start, end: start },
+            };
+            return Ok(Some(GotoDefinitionResponse::Scalar(location)));
+        }
+
+        Ok(None)
+    }
+
+    async fn will_rename_files(&self, params: RenameFilesParams) -> Result<Option<WorkspaceEdit>> {
+        self.client
+            .log_message(MessageType::INFO, "will rename files!")
+            .await;
+        let encoding = self.position_encoding.lock().await.clone();
+        let document_map = self.document_map.lock().await;
+        let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+
+        for rename in &params.files {
+            let (Ok(old_url), Ok(new_url)) = (
+                Url::from_str(&rename.old_uri),
+                Url::from_str(&rename.new_uri),
+            ) else {
+                continue;
+            };
+            let (Ok(old_path), Ok(new_path)) = (old_url.to_file_path(), new_url.to_file_path())
+            else {
+                continue;
+            };
+
+            for (doc_uri, rope) in document_map.iter() {
+                let Ok(doc_url) = Url::from_str(doc_uri) else {
+                    continue;
+                };
+                for (start, end, matched) in find_path_matches(rope) {
+                    let Some(resolved) = resolve_path(&matched, &doc_url) else {
+                        continue;
+                    };
+                    // Only rewrite references that point at the renamed path or
+                    // something nested beneath it.
+                    let Ok(suffix) = resolved.strip_prefix(&old_path) else {
+                        continue;
+                    };
+                    let new_resolved = new_path.join(suffix);
+                    let Some(new_text) = rewrite_path_literal(&matched, &new_resolved, &doc_url)
+                    else {
+                        continue;
+                    };
+                    let range = Range {
+                        start: offset_to_position(rope, start, &encoding),
+                        end: offset_to_position(rope, end, &encoding),
+                    };
+                    changes
+                        .entry(doc_url.clone())
+                        .or_default()
+                        .push(TextEdit { range, new_text });
                 }
-            })
-            .collect::<Vec<_>>();
+            }
+        }
 
-        Ok(Some(CompletionResponse::Array(items)))
+        if changes.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(WorkspaceEdit {
+                changes: Some(changes),
+                document_changes: None,
+                change_annotations: None,
+            }))
+        }
+    }
+
+    async fn did_rename_files(&self, params: RenameFilesParams) {
+        self.client
+            .log_message(MessageType::INFO, "did rename files!")
+            .await;
+        let mut document_map = self.document_map.lock().await;
+        for rename in &params.files {
+            if let Some(rope) = document_map.remove(&rename.old_uri) {
+                document_map.insert(rename.new_uri.clone(), rope);
+            }
+        }
     }
 }
 
-fn position_to_offset(rope: &Rope, position: Position) -> usize {
+/// Converts an LSP [`Position`] into a char offset into `rope`, interpreting
+/// `position.character` according to the negotiated `encoding`.
+///
+/// LSP clients express the character component in UTF-16 code units by default,
+/// so a naive `line_start + character` desyncs on any line containing multibyte
+/// or non-BMP characters before the cursor. We walk the requested line counting
+/// code units in the active encoding and map the result back to a char offset.
+fn position_to_offset(rope: &Rope, position: Position, encoding: &PositionEncodingKind) -> usize {
     let line_start = rope.line_to_char(position.line as usize);
-    line_start + position.character as usize
+    let character = position.character as usize;
+
+    if *encoding == PositionEncodingKind::UTF8 {
+        let line = rope.line(position.line as usize);
+        let mut units = 0;
+        let mut chars = 0;
+        for c in line.chars() {
+            if units >= character {
+                break;
+            }
+            units += c.len_utf8();
+            chars += 1;
+        }
+        line_start + chars
+    } else if *encoding == PositionEncodingKind::UTF32 {
+        line_start + character
+    } else {
+        let line = rope.line(position.line as usize);
+        let mut units = 0;
+        let mut chars = 0;
+        for c in line.chars() {
+            if units >= character {
+                break;
+            }
+            units += c.len_utf16();
+            chars += 1;
+        }
+        line_start + chars
+    }
+}
+
+/// Upper bound on how many indexed (project-wide) entries a single completion
+/// response carries, so a large workspace can't swamp the result.
+const MAX_INDEX_COMPLETIONS: usize = 100;
+
+/// Expresses `target` relative to `base`, inserting `..` components to climb out
+/// of `base` where the two paths diverge.
+fn relative_path(target: &Path, base: &Path) -> PathBuf {
+    let mut target = target.components().peekable();
+    let mut base = base.components().peekable();
+    while let (Some(t), Some(b)) = (target.peek(), base.peek()) {
+        if t == b {
+            target.next();
+            base.next();
+        } else {
+            break;
+        }
+    }
+    let mut relative = PathBuf::new();
+    for _ in base {
+        relative.push(Component::ParentDir);
+    }
+    for component in target {
+        relative.push(component);
+    }
+    relative
+}
+
+/// Resolves a matched path literal (as produced by [`get_path_suffix`]) to an
+/// absolute path. Mirrors the resolution used in `completion`: `file://` URLs
+/// are decoded, a leading `~` is expanded via [`expand_tilde`], and relative
+/// paths are joined against the directory containing `base_uri`.
+fn resolve_path(matched: &str, base_uri: &Url) -> Option<PathBuf> {
+    // Only `file://` URLs name filesystem paths; `http`/`ftp`/etc. references
+    // are not resolvable on disk and must not be treated as relative paths.
+    if let Some(index) = matched.find("://") {
+        if !matched[..index].eq_ignore_ascii_case("file") {
+            return None;
+        }
+    }
+    let path: Cow<_> = if matched.starts_with("file://") {
+        Url::from_str(matched)
+            .ok()
+            .and_then(|url| url.to_file_path().ok())?
+            .into()
+    } else {
+        Path::new(matched).into()
+    };
+    let path = expand_tilde(path);
+    let parent_dir = base_uri.to_file_path().ok()?;
+    let parent_dir = parent_dir.parent();
+    let path = match parent_dir {
+        Some(parent_dir) if path.is_relative() => parent_dir.join(&path),
+        _ => path.into_owned(),
+    };
+    Some(path)
+}
+
+/// Renders `new_resolved` back into a literal that keeps the same shape as the
+/// `original` match: a `file://` URL stays a URL, an absolute path stays
+/// absolute, and a relative reference stays relative to the document directory.
+fn rewrite_path_literal(original: &str, new_resolved: &Path, base_uri: &Url) -> Option<String> {
+    if original.starts_with("file://") {
+        Url::from_file_path(new_resolved)
+            .ok()
+            .map(|url| url.to_string())
+    } else if original.starts_with('~') {
+        // Preserve the `~` shorthand when the target still lives under $HOME.
+        if let Ok(home) = std::env::var("HOME") {
+            if let Ok(rest) = new_resolved.strip_prefix(&home) {
+                let mut tilde = PathBuf::from("~");
+                tilde.push(rest);
+                return Some(tilde.to_string_lossy().into_owned());
+            }
+        }
+        Some(new_resolved.to_string_lossy().into_owned())
+    } else if Path::new(original).is_absolute() {
+        Some(new_resolved.to_string_lossy().into_owned())
+    } else {
+        let parent_dir = base_uri.to_file_path().ok()?;
+        let parent_dir = parent_dir.parent()?;
+        // `..`-aware so references that move across directories stay relative.
+        Some(
+            relative_path(new_resolved, parent_dir)
+                .to_string_lossy()
+                .into_owned(),
+        )
+    }
+}
+
+/// Scans `rope` for every path-like match, returning each as a
+/// `(start_byte, end_byte, literal)` tuple.
+fn find_path_matches(rope: &Rope) -> Vec<(usize, usize, String)> {
+    static REGEX: Lazy<Regex> = Lazy::new(|| compile_path_regex("", "", false));
+    let slice = rope.slice(..);
+    REGEX
+        .find_iter(Input::new(slice))
+        .map(|mat| {
+            let range = mat.range();
+            (range.start, range.end, slice.byte_slice(range).to_string())
+        })
+        .collect()
+}
+
+/// The `workspace.fileOperations` registration used for both `willRename` and
+/// `didRename`: match every file-scheme path in the workspace.
+fn path_file_operation_options() -> FileOperationRegistrationOptions {
+    FileOperationRegistrationOptions {
+        filters: vec![FileOperationFilter {
+            scheme: Some("file".to_string()),
+            pattern: FileOperationPattern {
+                glob: "**/*".to_string(),
+                matches: None,
+                options: None,
+            },
+        }],
+    }
+}
+
+/// Converts a byte offset in `rope` back into an LSP [`Position`], counting the
+/// column in whatever code units `encoding` selected. The reverse direction of
+/// [`position_to_offset`], which yields a char offset rather than a byte offset.
+fn offset_to_position(rope: &Rope, byte: usize, encoding: &PositionEncodingKind) -> Position {
+    let char_idx = rope.byte_to_char(byte);
+    let line = rope.char_to_line(char_idx);
+    let line_start = rope.line_to_char(line);
+
+    let character = if *encoding == PositionEncodingKind::UTF32 {
+        char_idx - line_start
+    } else if *encoding == PositionEncodingKind::UTF8 {
+        rope.slice(line_start..char_idx)
+            .chars()
+            .map(|c| c.len_utf8())
+            .sum()
+    } else {
+        rope.slice(line_start..char_idx)
+            .chars()
+            .map(|c| c.len_utf16())
+            .sum()
+    };
+
+    Position {
+        line: line as u32,
+        character: character as u32,
+    }
+}
+
+/// Scans `rope` for path references and produces a warning [`Diagnostic`] for
+/// each one that does not exist on disk, distinguishing a missing final
+/// component (likely a typo) from a wholly missing path.
+fn compute_diagnostics(
+    rope: &Rope,
+    uri: &Url,
+    encoding: &PositionEncodingKind,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (start, end, matched) in find_path_matches(rope) {
+        let Some(resolved) = resolve_path(&matched, uri) else {
+            continue;
+        };
+        if resolved.exists() {
+            continue;
+        }
+        let parent_exists = resolved.parent().map(Path::exists).unwrap_or(false);
+        let message = if parent_exists {
+            format!("`{matched}` does not exist (parent directory is present)")
+        } else {
+            format!("`{matched}` does not exist")
+        };
+        let range = Range {
+            start: offset_to_position(rope, start, encoding),
+            end: offset_to_position(rope, end, encoding),
+        };
+        diagnostics.push(Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::WARNING),
+            source: Some("path".to_string()),
+            message,
+            ..Diagnostic::default()
+        });
+    }
+    diagnostics
+}
+
+/// Build/dependency directories pruned from the crawl even when the workspace
+/// has no `.gitignore` listing them, so the index isn't dominated by artifacts.
+const DEFAULT_IGNORED_DIRS: &[&str] = &["target", "node_modules", "dist", "build", ".git"];
+
+/// Walks `root` breadth-unaware, collecting workspace-relative file paths until
+/// the `config` file-count or memory budget is exhausted. Unless `all_files` is
+/// set, hidden entries, the root `.gitignore` patterns, and well-known build
+/// directories are skipped; symlinks are never followed.
+fn crawl_workspace(root: &Path, config: &Crawl) -> Vec<String> {
+    let patterns = if config.all_files {
+        Vec::new()
+    } else {
+        let mut patterns = load_gitignore(root);
+        patterns.extend(DEFAULT_IGNORED_DIRS.iter().map(|dir| dir.to_string()));
+        patterns
+    };
+
+    let mut index = Vec::new();
+    let mut memory = 0usize;
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if index.len() >= config.max_crawl_files || memory >= config.max_crawl_memory {
+                return index;
+            }
+            let file_name = entry.file_name();
+            let file_name = file_name.to_string_lossy();
+            if !config.all_files && file_name.starts_with('.') {
+                continue;
+            }
+            let Ok(meta) = entry.metadata() else {
+                continue;
+            };
+            if meta.is_symlink() {
+                continue;
+            }
+            let path = entry.path();
+            let Ok(relative) = path.strip_prefix(root) else {
+                continue;
+            };
+            if !config.all_files && is_ignored(relative, &file_name, &patterns) {
+                continue;
+            }
+            if meta.is_dir() {
+                stack.push(path);
+            } else if meta.is_file() {
+                let relative = relative.to_string_lossy().into_owned();
+                memory += relative.len();
+                index.push(relative);
+            }
+        }
+    }
+    index
+}
+
+/// Reads the root `.gitignore` into a list of patterns, skipping blanks,
+/// comments, and negations (a small subset — see [`is_ignored`]).
+fn load_gitignore(root: &Path) -> Vec<String> {
+    let mut patterns = Vec::new();
+    if let Ok(contents) = std::fs::read_to_string(root.join(".gitignore")) {
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+                continue;
+            }
+            patterns.push(line.to_string());
+        }
+    }
+    patterns
+}
+
+/// Returns whether `relative` (relative to the workspace root) or its file
+/// `name` matches one of the gitignore `patterns`. Supports a leading `/`
+/// anchor and `*` wildcards — enough for the common ignore entries without a
+/// full gitignore engine. A trailing `/` is stripped, so such a pattern matches
+/// files as well as directories of that name.
+fn is_ignored(relative: &Path, name: &str, patterns: &[String]) -> bool {
+    let relative = relative.to_string_lossy();
+    patterns.iter().any(|pattern| {
+        let anchored = pattern.starts_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+        if anchored || pattern.contains('/') {
+            glob_match(pattern, &relative)
+        } else {
+            glob_match(pattern, name)
+        }
+    })
+}
+
+/// Minimal glob matcher supporting `*` (any run, including separators) and `?`
+/// (single char), used only for the [`is_ignored`] gitignore subset.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    let (mut star, mut mark) = (None, 0);
+    while ti < text.len() {
+        if pi < pattern.len() && (pattern[pi] == text[ti] || pattern[pi] == '?') {
+            pi += 1;
+            ti += 1;
+        } else if pi < pattern.len() && pattern[pi] == '*' {
+            star = Some(pi);
+            mark = ti;
+            pi += 1;
+        } else if let Some(s) = star {
+            pi = s + 1;
+            mark += 1;
+            ti = mark;
+        } else {
+            return false;
+        }
+    }
+    while pi < pattern.len() && pattern[pi] == '*' {
+        pi += 1;
+    }
+    pi == pattern.len()
+}
+
+/// Scores `candidate` against a fuzzy `query`, returning `None` when `query` is
+/// not a subsequence of `candidate`. Higher is better: a shared prefix,
+/// consecutive character runs, and matches on word/camelCase boundaries all
+/// earn bonuses. An empty query matches everything with a neutral score.
+fn fuzzy_score(candidate: &str, query: &str) -> Option<i64> {
+    if query.is_empty() {
+        return Some(0);
+    }
+    let cand: Vec<char> = candidate.chars().collect();
+    let query_lower: Vec<char> = query.chars().flat_map(char::to_lowercase).collect();
+
+    let is_boundary = |i: usize| -> bool {
+        if i == 0 {
+            return true;
+        }
+        let prev = cand[i - 1];
+        let cur = cand[i];
+        matches!(prev, '_' | '-' | '.' | ' ' | '/') || (prev.is_lowercase() && cur.is_uppercase())
+    };
+
+    let mut score = 0i64;
+    let mut ci = 0usize;
+    let mut prev_match: Option<usize> = None;
+    for (qi, &qc) in query_lower.iter().enumerate() {
+        let mut matched = None;
+        while ci < cand.len() {
+            if cand[ci].to_lowercase().next() == Some(qc) {
+                matched = Some(ci);
+                break;
+            }
+            ci += 1;
+        }
+        let mi = matched?;
+        score += 1;
+        if mi > 0 && prev_match == Some(mi - 1) {
+            score += 15;
+        }
+        if is_boundary(mi) {
+            score += 10;
+        }
+        if mi == qi {
+            score += 5;
+        }
+        prev_match = Some(mi);
+        ci = mi + 1;
+    }
+    Some(score)
 }
 
 fn read_dir_sorted(path: &Path, show_hidden: bool) -> std::io::Result<Vec<DirEntry>> {
@@ -289,7 +1077,12 @@ async fn main() {
 
     let (service, socket) = LspService::new(|client| Backend {
         client,
-        document_map: Mutex::new(HashMap::default()),
+        document_map: Arc::new(Mutex::new(HashMap::default())),
+        position_encoding: Mutex::new(PositionEncodingKind::UTF16),
+        crawl_config: Mutex::new(Crawl::default()),
+        workspace_root: Mutex::new(None),
+        workspace_index: Mutex::new(Vec::new()),
+        diagnostics_version: Arc::new(Mutex::new(HashMap::default())),
     });
     Server::new(stdin, stdout, socket).serve(service).await;
 }